@@ -0,0 +1,91 @@
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use iroh_blobs::{ticket::BlobTicket, Hash};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub filename: String,
+    pub peer: String,
+    pub ticket: String,
+    pub size: u64,
+    pub timestamp: u64,
+    pub direction: TransferDirection,
+}
+
+/// Persistent, content-addressed log of every sent/received transfer, backed
+/// by an embedded `sled` database under the platform config dir. Because iroh
+/// hashes are content-addressed, the blob `Hash` doubles as both the record
+/// key and the dedupe key: a hash already present here means the blob doesn't
+/// need downloading again.
+#[derive(Clone)]
+pub struct FileCache {
+    db: sled::Db,
+}
+
+impl FileCache {
+    pub fn open() -> Result<Self> {
+        let mut dir = dirs::config_dir().context("could not determine config directory")?;
+        dir.push("mini-dropbox");
+        dir.push("transfers.sled");
+
+        let db = sled::open(&dir)
+            .with_context(|| format!("failed to open transfer cache at {}", dir.display()))?;
+
+        Ok(Self { db })
+    }
+
+    pub fn record(&self, hash: Hash, record: &TransferRecord) -> Result<()> {
+        let bytes = serde_json::to_vec(record).context("failed to serialize transfer record")?;
+        self.db
+            .insert(hash.as_bytes(), bytes)
+            .context("failed to write transfer record")?;
+        self.db.flush().context("failed to flush transfer cache")?;
+        Ok(())
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.db.contains_key(hash.as_bytes()).unwrap_or(false)
+    }
+
+    /// Same dedupe check as `contains`, but taking a raw ticket string so the
+    /// UI can show "already received" without having to parse the ticket itself.
+    pub fn already_have(&self, ticket: &str) -> bool {
+        BlobTicket::from_str(ticket)
+            .map(|t| self.contains(&t.hash()))
+            .unwrap_or(false)
+    }
+
+    pub fn history(&self) -> Vec<(Hash, TransferRecord)> {
+        let mut entries: Vec<_> = self
+            .db
+            .iter()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|(key, value)| {
+                let hash = Hash::from_bytes(key.as_ref().try_into().ok()?);
+                let record: TransferRecord = serde_json::from_slice(&value).ok()?;
+                Some((hash, record))
+            })
+            .collect();
+
+        entries.sort_by(|a, b| b.1.timestamp.cmp(&a.1.timestamp));
+        entries
+    }
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
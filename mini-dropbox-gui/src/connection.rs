@@ -0,0 +1,335 @@
+use std::{ops::ControlFlow, path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Context};
+use futures_util::{Sink, SinkExt, StreamExt};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    time,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::{
+    cache::{self, TransferDirection, TransferRecord},
+    events::AppEvent,
+    iroh_node::IrohNode,
+    message::WebSocketMessage,
+    qr,
+};
+
+const WS_URL: &str = "ws://3.107.184.180:4001/ws";
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+const PONG_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// A connection that stayed up at least this long is considered to have
+// recovered, not just to have flapped back on; its drop shouldn't keep
+// piling onto a backoff built up from earlier, unrelated failures.
+const STABLE_CONNECTION_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// Keeps the WebSocket connection alive for as long as the app runs.
+///
+/// Connects once, relays `WebSocketMessage`s in both directions, and pings the
+/// server on an interval. On any read/write/pong failure it reports
+/// `AppEvent::ConnectionLost`, waits with capped exponential backoff, and
+/// reconnects, automatically re-sending `Register` so the server's active
+/// users list picks the client back up. The very first connection instead
+/// lets the UI drive registration through `AppState::PublishUser`, same as
+/// before this supervisor existed. A connection that stays up past
+/// `STABLE_CONNECTION_THRESHOLD` resets the backoff counter, so a later drop
+/// starts reconnecting from scratch instead of inheriting whatever attempt
+/// count a much earlier, unrelated outage left behind.
+pub async fn supervise(
+    tx: Sender<AppEvent>,
+    mut from_ui: Receiver<WebSocketMessage>,
+    iroh_node: IrohNode,
+    nickname: String,
+) {
+    let mut attempt: u32 = 0;
+
+    loop {
+        let is_reconnect = attempt > 0;
+
+        if is_reconnect {
+            tx.send(AppEvent::Reconnecting { attempt }).await.ok();
+        }
+
+        let connected_at = time::Instant::now();
+
+        if connect_and_run(&tx, &mut from_ui, &iroh_node, &nickname, is_reconnect)
+            .await
+            .is_err()
+        {
+            tx.send(AppEvent::ConnectionLost).await.ok();
+        }
+
+        if connected_at.elapsed() >= STABLE_CONNECTION_THRESHOLD {
+            attempt = 0;
+        }
+
+        attempt += 1;
+        time::sleep(backoff_for(attempt)).await;
+    }
+}
+
+fn backoff_for(attempt: u32) -> Duration {
+    let millis = 500u64.saturating_mul(1u64 << attempt.saturating_sub(1).min(6));
+    Duration::from_millis(millis).min(MAX_BACKOFF)
+}
+
+async fn connect_and_run(
+    tx: &Sender<AppEvent>,
+    from_ui: &mut Receiver<WebSocketMessage>,
+    iroh_node: &IrohNode,
+    nickname: &str,
+    is_reconnect: bool,
+) -> anyhow::Result<()> {
+    let (ws_stream, _) = connect_async(WS_URL)
+        .await
+        .context("WebSocket connection failed")?;
+    let (mut sender, mut receiver) = ws_stream.split();
+
+    // Every connection starts on JSON text framing, since we don't yet know
+    // whether the server has rolled out MessagePack support and it needs to
+    // be able to decode this connection's very first `Register`. We only
+    // upgrade to binary MessagePack frames once `RegisterSuccess` echoes
+    // `supports_msgpack: true` back - a legacy server that doesn't know the
+    // field at all still replies with the same `RegisterSuccess` shape but
+    // `false`, so staying in lockstep with its actual answer (not just its
+    // presence) keeps JSON-only servers working.
+    let mut use_msgpack = false;
+
+    if is_reconnect {
+        let register = WebSocketMessage::Register {
+            nickname: nickname.to_string(),
+            supports_msgpack: true,
+        };
+        send_framed(&mut sender, &register, use_msgpack).await?;
+    } else {
+        tx.send(AppEvent::ReadyToPublishUser).await.ok();
+    }
+
+    let mut ping_interval = time::interval(PING_INTERVAL);
+    ping_interval.tick().await; // the first tick fires immediately; skip it
+    let mut ping_sent_at: Option<time::Instant> = None;
+
+    // Transfers (a send's blob-add, a receive's download) run on their own
+    // tasks so a multi-MB file doesn't block this loop from pinging, reading
+    // incoming frames, or starting a second transfer. A spawned send reports
+    // its finished ticket back here over this channel so it can still be
+    // framed and written by the one task that owns `sender`.
+    let (to_socket_tx, mut to_socket_rx) = mpsc::channel::<WebSocketMessage>(32);
+
+    loop {
+        tokio::select! {
+            ws_msg = receiver.next() => {
+                match ws_msg {
+                    Some(Ok(Message::Pong(_))) => {
+                        ping_sent_at = None;
+                    }
+                    Some(Ok(msg)) => {
+                        match decode(msg) {
+                            Ok(Some(WebSocketMessage::UseJsonFallback)) => {
+                                use_msgpack = false;
+                            }
+                            Ok(Some(WebSocketMessage::RegisterSuccess { supports_msgpack })) => {
+                                use_msgpack = supports_msgpack;
+                                tx.send(AppEvent::RegisterSuccess).await.ok();
+                            }
+                            Ok(Some(decoded)) => {
+                                if handle_incoming(decoded, tx).await.is_break() {
+                                    return Err(anyhow!("connection closed by server"));
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                tx.send(AppEvent::FatalError(
+                                    anyhow!(e).context("Message parse failed"),
+                                ))
+                                .await
+                                .ok();
+                            }
+                        }
+                    }
+                    Some(Err(e)) => return Err(anyhow::Error::new(e).context("WebSocket read error")),
+                    None => return Err(anyhow!("WebSocket stream ended")),
+                }
+            }
+            ui_msg = from_ui.recv() => {
+                let Some(ui_msg) = ui_msg else {
+                    return Err(anyhow!("UI message channel closed"));
+                };
+                handle_outgoing(ui_msg, iroh_node, tx, &to_socket_tx).await?;
+            }
+            out_msg = to_socket_rx.recv() => {
+                if let Some(out_msg) = out_msg {
+                    send_framed(&mut sender, &out_msg, use_msgpack).await?;
+                }
+            }
+            _ = ping_interval.tick() => {
+                if let Some(sent_at) = ping_sent_at {
+                    if sent_at.elapsed() > PONG_TIMEOUT {
+                        return Err(anyhow!("no pong received within {PONG_TIMEOUT:?}, connection considered dropped"));
+                    }
+                }
+                sender.send(Message::Ping(Vec::new().into())).await.context("Ping send failed")?;
+                ping_sent_at = Some(time::Instant::now());
+            }
+        }
+    }
+}
+
+/// Decodes a data frame into a `WebSocketMessage`. Control frames we don't
+/// otherwise special-case (e.g. `Close`) carry no payload and decode to `None`.
+fn decode(msg: Message) -> anyhow::Result<Option<WebSocketMessage>> {
+    match msg {
+        Message::Text(bytes) => Ok(Some(serde_json::from_str(bytes.as_str())?)),
+        Message::Binary(bytes) => Ok(Some(rmp_serde::from_slice(&bytes)?)),
+        _ => Ok(None),
+    }
+}
+
+async fn send_framed<S>(sender: &mut S, msg: &WebSocketMessage, use_msgpack: bool) -> anyhow::Result<()>
+where
+    S: Sink<Message> + Unpin,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let frame = if use_msgpack {
+        Message::Binary(msg.to_msgpack().into())
+    } else {
+        Message::Text(msg.to_json().into())
+    };
+    sender.send(frame).await.context("Websocket send failed")
+}
+
+/// Handles a message from the UI. `PrepareFile` and `AcceptFile` drive a
+/// whole blob add/download to completion, so each is spawned onto its own
+/// task and reports back through `tx` (progress/outcome) and `to_socket` (the
+/// `SendFile` frame once a send is ready) instead of being awaited here -
+/// otherwise a multi-MB transfer would block this connection's ping/pong and
+/// incoming-frame handling for as long as it runs. Everything else is a
+/// cheap, already-built message that just needs writing to the socket, so
+/// it's handed straight to `to_socket` for the run loop to frame and send.
+async fn handle_outgoing(
+    msg: WebSocketMessage,
+    iroh_node: &IrohNode,
+    tx: &Sender<AppEvent>,
+    to_socket: &Sender<WebSocketMessage>,
+) -> anyhow::Result<()> {
+    match msg {
+        WebSocketMessage::PrepareFile(abs_path) => {
+            let iroh_node = iroh_node.clone();
+            let tx = tx.clone();
+            let to_socket = to_socket.clone();
+
+            tokio::spawn(async move {
+                if let Err(e) = prepare_and_send_file(abs_path, &iroh_node, &tx, &to_socket).await {
+                    tx.send(AppEvent::FatalError(e)).await.ok();
+                }
+            });
+        }
+        WebSocketMessage::AcceptFile { from, ticket, save_path } => {
+            let iroh_node = iroh_node.clone();
+            let tx = tx.clone();
+
+            tokio::spawn(async move {
+                let id = ticket.clone();
+                match iroh_node.receive_file(&from, &ticket, save_path, &id, &tx).await {
+                    Ok(()) => {
+                        tx.send(AppEvent::DownloadComplete { ticket }).await.ok();
+                    }
+                    Err(e) => {
+                        tx.send(AppEvent::TransferComplete { id }).await.ok();
+                        tx.send(AppEvent::DownloadFailed { ticket: ticket.clone() }).await.ok();
+                        tx.send(AppEvent::FatalError(e)).await.ok();
+                    }
+                }
+            });
+        }
+        other => {
+            to_socket
+                .send(other)
+                .await
+                .context("connection run loop gone")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds `abs_path` to the blob store, then queues the resulting `SendFile`
+/// ticket onto `to_socket` and records it in the transfer history. Runs on
+/// its own task (see `handle_outgoing`), so all failures are reported through
+/// `tx` rather than propagated to a caller.
+async fn prepare_and_send_file(
+    abs_path: PathBuf,
+    iroh_node: &IrohNode,
+    tx: &Sender<AppEvent>,
+    to_socket: &Sender<WebSocketMessage>,
+) -> anyhow::Result<()> {
+    let filename = abs_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let size = std::fs::metadata(&abs_path).map(|m| m.len()).unwrap_or(0);
+
+    let id = abs_path.to_string_lossy().into_owned();
+    let tag = iroh_node.add_path_with_progress(abs_path, &id, tx).await?;
+    let node_id = iroh_node.endpoint.id();
+
+    let ticket =
+        iroh_blobs::ticket::BlobTicket::new(node_id.into(), tag.hash, tag.format).to_string();
+
+    to_socket
+        .send(WebSocketMessage::SendFile(ticket.clone()))
+        .await
+        .context("connection run loop gone")?;
+
+    iroh_node.cache.record(
+        tag.hash,
+        &TransferRecord {
+            filename,
+            peer: "(unknown)".to_string(),
+            ticket: ticket.clone(),
+            size,
+            timestamp: cache::now_unix(),
+            direction: TransferDirection::Sent,
+        },
+    )?;
+
+    match qr::render_ticket_qr(&ticket) {
+        Ok(image) => {
+            tx.send(AppEvent::TicketQrReady { ticket, image }).await.ok();
+        }
+        Err(e) => {
+            tx.send(AppEvent::FatalError(e.context("failed to render ticket QR code")))
+                .await
+                .ok();
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_incoming(websocket_msg: WebSocketMessage, tx: &Sender<AppEvent>) -> ControlFlow<(), ()> {
+    match websocket_msg {
+        WebSocketMessage::ErrorDeserializingJson(e) => {
+            tx.send(AppEvent::FatalError(
+                anyhow!(e).context("Server JSON error"),
+            ))
+            .await
+            .ok();
+        }
+        WebSocketMessage::ActiveUsersList(active_users_list) => {
+            tx.send(AppEvent::UpdateActiveUsersList(active_users_list))
+                .await
+                .ok();
+        }
+        WebSocketMessage::ReceiveFile { from, ticket } => {
+            tx.send(AppEvent::IncomingFile { from, ticket })
+                .await
+                .ok();
+        }
+        _ => {}
+    }
+
+    ControlFlow::Continue(())
+}
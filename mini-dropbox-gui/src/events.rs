@@ -5,5 +5,17 @@ pub enum AppEvent {
 
     UpdateActiveUsersList(Vec<String>),
 
+    IncomingFile { from: String, ticket: String },
+    DownloadComplete { ticket: String },
+    DownloadFailed { ticket: String },
+
+    ConnectionLost,
+    Reconnecting { attempt: u32 },
+
+    TicketQrReady { ticket: String, image: egui::ColorImage },
+
+    TransferProgress { id: String, name: String, transferred: u64, total: u64 },
+    TransferComplete { id: String },
+
     FatalError(anyhow::Error),
 }
@@ -0,0 +1,193 @@
+use std::{path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use iroh::{protocol::Router, Endpoint};
+use iroh_blobs::{
+    get::db::DownloadProgress, net_protocol::Blobs, store::fs::Store, ticket::BlobTicket,
+    util::progress::AddProgress, Tag,
+};
+use tokio::{sync::mpsc::Sender, time::Instant};
+
+use crate::{
+    cache::{self, FileCache, TransferDirection, TransferRecord},
+    events::AppEvent,
+};
+
+/// How often progress events are forwarded to the UI. iroh reports progress far
+/// more often than the UI can usefully redraw, so we throttle to this interval.
+const PROGRESS_THROTTLE: Duration = Duration::from_millis(100);
+
+/// Owns this client's iroh endpoint and blob store for the lifetime of the app.
+///
+/// Cheap to clone: `endpoint` and `store` are themselves handles, `cache`
+/// wraps a `sled::Db` handle, and `_router` is `Arc`-wrapped here so a clone
+/// spawned onto its own task for a single transfer can outlive the call that
+/// made it without tearing down the router's accept loop early.
+#[derive(Clone)]
+pub struct IrohNode {
+    pub endpoint: Endpoint,
+    pub store: Store,
+    pub cache: FileCache,
+    _router: Arc<Router>,
+}
+
+impl IrohNode {
+    pub async fn new(cache: FileCache) -> Result<Self> {
+        let endpoint = Endpoint::builder()
+            .discovery_n0()
+            .bind()
+            .await
+            .context("failed to bind iroh endpoint")?;
+
+        let mut blobs_dir = dirs::config_dir().context("could not determine config directory")?;
+        blobs_dir.push("mini-dropbox");
+        blobs_dir.push("blobs");
+
+        let blobs = Blobs::persistent(&blobs_dir)
+            .await
+            .with_context(|| format!("failed to open blob store at {}", blobs_dir.display()))?
+            .build(&endpoint);
+
+        let router = Router::builder(endpoint.clone())
+            .accept(iroh_blobs::ALPN, blobs.clone())
+            .spawn()
+            .await
+            .context("failed to spawn iroh router")?;
+
+        Ok(Self {
+            endpoint,
+            store: blobs.store().clone(),
+            cache,
+            _router: Arc::new(router),
+        })
+    }
+
+    /// Downloads the blob described by `ticket` from its remote node and exports the
+    /// completed transfer to `save_path`. If we already have this content-addressed
+    /// hash from a previous transfer, the download is skipped entirely. Progress is
+    /// reported under `id` (the ticket string, so the UI can match it back up to the
+    /// `AcceptFile` that started it) with `save_path`'s file name as the human-readable label.
+    pub async fn receive_file(
+        &self,
+        from: &str,
+        ticket: &str,
+        save_path: PathBuf,
+        id: &str,
+        tx: &Sender<AppEvent>,
+    ) -> Result<()> {
+        let ticket = BlobTicket::from_str(ticket).context("invalid blob ticket")?;
+        let node_addr = ticket.node_addr().clone();
+        let hash = ticket.hash();
+        let format = ticket.format();
+
+        let filename = save_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        if !self.cache.contains(&hash) {
+            let mut progress = self
+                .store
+                .blobs()
+                .download(hash, node_addr)
+                .await
+                .context("failed to start blob download")?;
+
+            let mut total: u64 = 0;
+            let mut last_reported = Instant::now() - PROGRESS_THROTTLE;
+
+            while let Some(event) = progress.next().await {
+                match event.context("download stream failed")? {
+                    DownloadProgress::FoundLocal { size, .. } | DownloadProgress::Found { size, .. } => {
+                        total = size.value();
+                    }
+                    DownloadProgress::Progress { offset, .. } => {
+                        if last_reported.elapsed() >= PROGRESS_THROTTLE {
+                            tx.send(AppEvent::TransferProgress {
+                                id: id.to_string(),
+                                name: filename.clone(),
+                                transferred: offset,
+                                total,
+                            })
+                            .await
+                            .ok();
+                            last_reported = Instant::now();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            tx.send(AppEvent::TransferComplete { id: id.to_string() }).await.ok();
+        }
+
+        self.store
+            .blobs()
+            .export(hash, format, save_path.clone())
+            .await
+            .context("failed to export downloaded blob")?;
+
+        let size = std::fs::metadata(&save_path).map(|m| m.len()).unwrap_or(0);
+
+        self.cache.record(
+            hash,
+            &TransferRecord {
+                filename,
+                peer: from.to_string(),
+                ticket: ticket.to_string(),
+                size,
+                timestamp: cache::now_unix(),
+                direction: TransferDirection::Received,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Adds `path` to the blob store, reporting throttled progress under `id` (the
+    /// file's absolute path, since no ticket exists yet at this point) with the
+    /// file's name as the human-readable label.
+    pub async fn add_path_with_progress(
+        &self,
+        path: PathBuf,
+        id: &str,
+        tx: &Sender<AppEvent>,
+    ) -> Result<Tag> {
+        let filename = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| id.to_string());
+        let total = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let mut progress = self.store.blobs().add_path_with_opts(path.into()).await?;
+
+        let mut last_reported = Instant::now() - PROGRESS_THROTTLE;
+        let mut tag = None;
+
+        while let Some(event) = progress.next().await {
+            match event.context("add stream failed")? {
+                AddProgress::Progress { offset, .. } => {
+                    if last_reported.elapsed() >= PROGRESS_THROTTLE {
+                        tx.send(AppEvent::TransferProgress {
+                            id: id.to_string(),
+                            name: filename.clone(),
+                            transferred: offset,
+                            total,
+                        })
+                        .await
+                        .ok();
+                        last_reported = Instant::now();
+                    }
+                }
+                AddProgress::Done { tag: done_tag, .. } => {
+                    tag = Some(done_tag);
+                }
+                _ => {}
+            }
+        }
+
+        tx.send(AppEvent::TransferComplete { id: id.to_string() }).await.ok();
+
+        tag.context("blob store closed the progress stream without completing")
+    }
+}
@@ -1,25 +1,24 @@
-use std::{clone, ops::ControlFlow, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, time::Instant};
 
 use anyhow::{anyhow, Context};
 use eframe::CreationContext;
-use egui::{vec2, Align2, Vec2};
-use egui_toast::{Toast, ToastKind, ToastOptions, Toasts};
-use futures_util::{SinkExt, StreamExt};
-use iroh_blobs::ticket::BlobTicket;
+use egui::Align2;
+use egui_toast::{ToastKind, Toasts};
 use names::{Generator, Name};
 use rfd::FileDialog;
-use serde_json::json;
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio_tungstenite::{
-    connect_async,
-    tungstenite::{Bytes, Message, Utf8Bytes},
-};
 
-use crate::{events::AppEvent, iroh_node::IrohNode, message::WebSocketMessage, state::AppState};
+use crate::{
+    cache::FileCache, events::AppEvent, iroh_node::IrohNode, message::WebSocketMessage,
+    state::AppState,
+};
 
+mod cache;
+mod connection;
 mod events;
 mod iroh_node;
 mod message;
+mod qr;
 mod state;
 mod toast;
 
@@ -39,6 +38,19 @@ async fn main() -> eframe::Result {
     )
 }
 
+/// Tracked per active transfer `id` (an absolute file path for sends, a ticket
+/// string for receives) so the UI can render a progress bar and throughput.
+/// `name` is the human-readable filename to display, since `id` itself isn't
+/// always presentable (a receive's `id` is the ticket string, not a path).
+struct TransferProgress {
+    name: String,
+    transferred: u64,
+    total: u64,
+    last_transferred: u64,
+    last_update: Instant,
+    throughput_bytes_per_sec: f64,
+}
+
 pub struct MyApp {
     app_state: AppState,
     nickname: String,
@@ -49,6 +61,10 @@ pub struct MyApp {
     tx: Sender<AppEvent>,
     rx: Receiver<AppEvent>,
     to_ws: Sender<WebSocketMessage>,
+    ticket_qr: Option<(String, egui::TextureHandle)>,
+    cache: FileCache,
+    show_history: bool,
+    transfers: HashMap<String, TransferProgress>,
 }
 
 impl MyApp {
@@ -60,6 +76,8 @@ impl MyApp {
             .anchor(Align2::RIGHT_TOP, (-10., 10.))
             .order(egui::Order::Tooltip);
 
+        let cache = FileCache::open().expect("failed to open transfer cache");
+
         Self {
             app_state: AppState::OnStartup(Some(from_ui)),
             files: vec![],
@@ -70,6 +88,10 @@ impl MyApp {
             toasts,
             rx,
             tx,
+            ticket_qr: None,
+            cache,
+            show_history: false,
+            transfers: HashMap::new(),
         }
     }
 }
@@ -103,6 +125,54 @@ impl eframe::App for MyApp {
                     AppEvent::UpdateActiveUsersList(active_users_list) => {
                         self.active_users_list = active_users_list;
                     }
+                    AppEvent::IncomingFile { from, ticket } => {
+                        self.app_state = AppState::IncomingFileRequest { from, ticket };
+                    }
+                    AppEvent::DownloadComplete { ticket } => {
+                        self.show_toast(format!("Received file for ticket {ticket}"), ToastKind::Success);
+                        self.app_state = AppState::Ready;
+                    }
+                    AppEvent::DownloadFailed { .. } => {
+                        self.app_state = AppState::Ready;
+                    }
+                    AppEvent::ConnectionLost => {
+                        self.show_toast("Connection lost, reconnecting...", ToastKind::Warning);
+                        self.app_state = AppState::Disconnected;
+                    }
+                    AppEvent::Reconnecting { attempt } => {
+                        self.app_state = AppState::Reconnecting { attempt };
+                    }
+                    AppEvent::TicketQrReady { ticket, image } => {
+                        let texture =
+                            ctx.load_texture(format!("qr-{ticket}"), image, Default::default());
+                        self.ticket_qr = Some((ticket, texture));
+                    }
+                    AppEvent::TransferProgress { id, name, transferred, total } => {
+                        let now = Instant::now();
+                        self.transfers
+                            .entry(id)
+                            .and_modify(|t| {
+                                let elapsed = now.duration_since(t.last_update).as_secs_f64();
+                                if elapsed > 0. {
+                                    t.throughput_bytes_per_sec =
+                                        (transferred.saturating_sub(t.last_transferred)) as f64 / elapsed;
+                                }
+                                t.transferred = transferred;
+                                t.last_transferred = transferred;
+                                t.last_update = now;
+                            })
+                            .or_insert(TransferProgress {
+                                name,
+                                transferred,
+                                total,
+                                last_transferred: transferred,
+                                last_update: now,
+                                throughput_bytes_per_sec: 0.,
+                            });
+                    }
+                    AppEvent::TransferComplete { id } => {
+                        self.transfers.remove(&id);
+                    }
                     AppEvent::FatalError(e) => {
                         self.show_toast(format!("{e:#}"), ToastKind::Error);
                     }
@@ -111,109 +181,28 @@ impl eframe::App for MyApp {
 
             match &mut self.app_state {
                 AppState::OnStartup(from_ui) => {
-                    let mut from_ui = from_ui.take().unwrap();
+                    let from_ui = from_ui.take().unwrap();
 
                     // get nickname
                     let mut generator = Generator::with_naming(Name::Numbered);
                     self.nickname = generator.next().unwrap_or("Guest".into());
 
-                    // setup ws
                     let tx = self.tx.clone();
+                    let nickname = self.nickname.clone();
+                    let cache = self.cache.clone();
 
                     tokio::spawn(async move {
-                        let ws_init = async {
-                            let ws_stream = connect_async("ws://3.107.184.180:4001/ws")
-                                .await
-                                .context("WebSocket connection failed")?;
-
-                            let (sender, receiver) = ws_stream.0.split();
-                            Ok::<_, anyhow::Error>((sender, receiver))
-                        };
-
-                        let iroh_init = async {
-                            IrohNode::new()
-                                .await
-                                .context("Iroh node initialization failed")
-                        };
-
-                        tokio::spawn(async move {
-                            match tokio::try_join!(ws_init, iroh_init) {
-                                Ok(((mut sender, mut receiver), iroh_node)) => {
-                                    // get ws msg
-                                    let tx_clone = tx.clone();
-                                    tokio::spawn(async move {
-                                        loop {
-                                            if let Some(Ok(msg)) = receiver.next().await {
-                                                if process_message(msg, tx_clone.clone())
-                                                    .await
-                                                    .is_break()
-                                                {
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                    });
-
-                                    // send ws msg
-                                    let tx_clone = tx.clone();
-                                    tokio::spawn(async move {
-                                        while let Some(websocket_msg) = from_ui.recv().await {
-                                            match websocket_msg {
-                                                WebSocketMessage::PrepareFile(abs_path) => {
-                                                    let tag = iroh_node
-                                                        .store
-                                                        .blobs()
-                                                        .add_path(abs_path)
-                                                        .await
-                                                        .unwrap();
-
-                                                    let node_id = iroh_node.endpoint.id();
-
-                                                    let ticket = BlobTicket::new(
-                                                        node_id.into(),
-                                                        tag.hash,
-                                                        tag.format,
-                                                    )
-                                                    .to_string();
-
-                                                    let json = WebSocketMessage::SendFile(ticket)
-                                                        .to_json();
-
-                                                    if let Err(e) = sender
-                                                        .send(Message::Text(json.into()))
-                                                        .await
-                                                        .context("Websocket send failed")
-                                                    {
-                                                        tx_clone
-                                                            .send(AppEvent::FatalError(e))
-                                                            .await
-                                                            .ok();
-                                                    }
-                                                }
-                                                _ => {
-                                                    let json = websocket_msg.to_json();
-                                                    if let Err(e) = sender
-                                                        .send(Message::Text(json.into()))
-                                                        .await
-                                                        .context("Websocket send failed")
-                                                    {
-                                                        tx_clone
-                                                            .send(AppEvent::FatalError(e))
-                                                            .await
-                                                            .ok();
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    });
-
-                                    tx.send(AppEvent::ReadyToPublishUser).await.ok();
-                                }
-                                Err(e) => {
-                                    tx.send(AppEvent::FatalError(e)).await.ok();
-                                }
+                        match IrohNode::new(cache)
+                            .await
+                            .context("Iroh node initialization failed")
+                        {
+                            Ok(iroh_node) => {
+                                connection::supervise(tx, from_ui, iroh_node, nickname).await;
                             }
-                        });
+                            Err(e) => {
+                                tx.send(AppEvent::FatalError(e)).await.ok();
+                            }
+                        }
                     });
 
                     self.app_state = AppState::Connecting;
@@ -227,9 +216,26 @@ impl eframe::App for MyApp {
                         })
                     });
                 }
+                AppState::Reconnecting { attempt } => {
+                    let attempt = *attempt;
+                    ui.centered_and_justified(|ui| {
+                        ui.add_space(ui.available_height() / 2.);
+                        ui.vertical_centered_justified(|ui| {
+                            ui.add(egui::Spinner::new().size(32.));
+                            ui.label(format!("Reconnecting (attempt {attempt})..."));
+                        })
+                    });
+                }
+                AppState::Disconnected => {
+                    ui.centered_and_justified(|ui| {
+                        ui.add_space(ui.available_height() / 2.);
+                        ui.label("Connection lost.");
+                    });
+                }
                 AppState::PublishUser => {
                     if let Err(e) = self.to_ws.try_send(WebSocketMessage::Register {
                         nickname: self.nickname.clone(),
+                        supports_msgpack: true,
                     }) {
                         self.tx
                             .try_send(AppEvent::FatalError(
@@ -246,6 +252,13 @@ impl eframe::App for MyApp {
                         self.files.push(files);
                     }
 
+                    if ui
+                        .button(if self.show_history { "Hide history" } else { "Show history" })
+                        .clicked()
+                    {
+                        self.show_history = !self.show_history;
+                    }
+
                     if !self.files.is_empty() {
                         self.files.iter().for_each(|p| {
                             ui.horizontal(|ui| {
@@ -304,9 +317,129 @@ impl eframe::App for MyApp {
                         });
                     }
                 }
+                AppState::IncomingFileRequest { from, ticket } => {
+                    let from = from.clone();
+                    let ticket = ticket.clone();
+
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label(format!("{from} wants to send you a file"));
+
+                        if self.cache.already_have(&ticket) {
+                            ui.label("(you already have this file)");
+                        }
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Accept").clicked() {
+                                if let Some(save_path) = FileDialog::new().save_file() {
+                                    if let Err(e) =
+                                        self.to_ws.try_send(WebSocketMessage::AcceptFile {
+                                            from: from.clone(),
+                                            ticket: ticket.clone(),
+                                            save_path,
+                                        })
+                                    {
+                                        self.tx
+                                            .try_send(AppEvent::FatalError(
+                                                anyhow!(e).context("failed to send websocket msg"),
+                                            ))
+                                            .ok();
+                                    }
+                                    self.app_state = AppState::Downloading { ticket };
+                                }
+                            }
+
+                            if ui.button("Decline").clicked() {
+                                self.app_state = AppState::Ready;
+                            }
+                        });
+                    });
+                }
+                AppState::Downloading { .. } => {
+                    ui.centered_and_justified(|ui| {
+                        ui.vertical_centered_justified(|ui| {
+                            ui.add(egui::Spinner::new().size(32.));
+                            ui.label("Receiving file...");
+                        });
+                    });
+                }
                 _ => {}
             }
 
+            if !self.transfers.is_empty() {
+                egui::Window::new("Transfers").show(ctx, |ui| {
+                    for progress in self.transfers.values() {
+                        let fraction = if progress.total > 0 {
+                            progress.transferred as f32 / progress.total as f32
+                        } else {
+                            0.
+                        };
+
+                        ui.label(format!(
+                            "{} ({:.1} KB/s)",
+                            progress.name,
+                            progress.throughput_bytes_per_sec / 1024.
+                        ));
+                        ui.add(egui::ProgressBar::new(fraction).show_percentage());
+                    }
+                });
+            }
+
+            if self.show_history {
+                egui::Window::new("Transfer history").show(ctx, |ui| {
+                    for (_hash, record) in self.cache.history() {
+                        ui.horizontal(|ui| {
+                            let direction = match record.direction {
+                                cache::TransferDirection::Sent => "sent to",
+                                cache::TransferDirection::Received => "received from",
+                            };
+                            ui.label(format!(
+                                "{} ({direction} {}, {} bytes)",
+                                record.filename, record.peer, record.size
+                            ));
+
+                            if record.direction == cache::TransferDirection::Received {
+                                if ui.button("Re-download").clicked() {
+                                    if let Some(save_path) = FileDialog::new().save_file() {
+                                        if let Err(e) =
+                                            self.to_ws.try_send(WebSocketMessage::AcceptFile {
+                                                from: record.peer.clone(),
+                                                ticket: record.ticket.clone(),
+                                                save_path,
+                                            })
+                                        {
+                                            self.tx
+                                                .try_send(AppEvent::FatalError(anyhow!(e).context(
+                                                    "failed to send websocket msg",
+                                                )))
+                                                .ok();
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                    }
+                });
+            }
+
+            if let Some((ticket, texture)) = self.ticket_qr.clone() {
+                let mut open = true;
+
+                egui::Window::new("Scan to receive")
+                    .open(&mut open)
+                    .show(ctx, |ui| {
+                        ui.image((texture.id(), texture.size_vec2()));
+                        ui.add_space(5.0);
+
+                        if ui.button("Copy ticket").clicked() {
+                            ui.output_mut(|o| o.copied_text = ticket.clone());
+                        }
+                    });
+
+                if !open {
+                    self.ticket_qr = None;
+                }
+            }
+
             self.toasts.show(ctx);
         });
     }
@@ -324,41 +457,3 @@ impl eframe::App for MyApp {
         }
     }
 }
-
-async fn process_message(msg: Message, tx: Sender<AppEvent>) -> ControlFlow<(), ()> {
-    match msg {
-        Message::Text(bytes) => match serde_json::from_str::<WebSocketMessage>(bytes.as_str()) {
-            Ok(websocket_msg) => match websocket_msg {
-                WebSocketMessage::RegisterSuccess => {
-                    tx.send(AppEvent::RegisterSuccess).await.ok();
-                }
-                WebSocketMessage::ErrorDeserializingJson(e) => {
-                    tx.send(AppEvent::FatalError(
-                        anyhow!(e).context("Server JSON error"),
-                    ))
-                    .await
-                    .ok();
-                }
-                WebSocketMessage::ActiveUsersList(active_users_list) => {
-                    tx.send(AppEvent::UpdateActiveUsersList(active_users_list))
-                        .await
-                        .ok();
-                }
-                WebSocketMessage::ReceiveFile(ticket) => {
-                    println!("ticket is {ticket}");
-                }
-                _ => {}
-            },
-            Err(e) => {
-                tx.send(AppEvent::FatalError(
-                    anyhow!(e).context("Message parse failed"),
-                ))
-                .await
-                .ok();
-            }
-        },
-        _ => {}
-    }
-
-    ControlFlow::Continue(())
-}
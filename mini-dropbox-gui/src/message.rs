@@ -1,12 +1,46 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "type", content = "payload", rename_all = "snake_case")]
 pub enum WebSocketMessage {
-    Register { nickname: String },
+    // `supports_msgpack` advertises that we're willing to switch to
+    // MessagePack framing. This `Register` itself always goes out as JSON
+    // text, so a server that hasn't rolled out MessagePack support yet can
+    // still decode it; it just echoes `supports_msgpack: false` back on
+    // `RegisterSuccess` and we stay on text frames.
+    Register { nickname: String, supports_msgpack: bool },
     DisconnectUser(String),
+    GetActiveUsersList(String),
+    ActiveUsersList(Vec<String>),
+
+    // Internal-only: intercepted on the sending side before it ever reaches
+    // the wire, so the blob gets added and turned into a `SendFile` ticket first.
+    PrepareFile(PathBuf),
+    SendFile(String),
+    ReceiveFile { from: String, ticket: String },
+
+    // Internal-only: sent from the UI once the user accepts an incoming
+    // transfer (or re-downloads one from history), so the task that owns the
+    // `IrohNode` can drive the download.
+    AcceptFile { from: String, ticket: String, save_path: PathBuf },
 
-    RegisterSuccess,
+    // `supports_msgpack` echoes back whether the server understood and
+    // accepts our `Register`'s `supports_msgpack` flag; only this affirmative
+    // ack upgrades the connection to MessagePack framing.
+    RegisterSuccess { supports_msgpack: bool },
+    UseJsonFallback,
 
     ErrorDeserializingJson(String),
 }
+
+impl WebSocketMessage {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("WebSocketMessage always serializes")
+    }
+
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        rmp_serde::to_vec(self).expect("WebSocketMessage always serializes")
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use egui::{Color32, ColorImage};
+use qrencode::QrCode;
+
+const QUIET_ZONE: usize = 4;
+// Long BlobTickets produce a dense QR code; at one pixel per module it's
+// only a few dozen pixels wide and unreadable to most phone cameras. Blow
+// each module up to a block of pixels so the rendered image is actually
+// scannable.
+const MODULE_SCALE: usize = 8;
+
+/// Renders a `BlobTicket` string as a black-on-white QR code, with a
+/// `QUIET_ZONE`-module quiet-zone border so scanners can find the finder patterns.
+pub fn render_ticket_qr(ticket: &str) -> Result<ColorImage> {
+    let code = QrCode::new(ticket.as_bytes()).context("failed to encode ticket as QR code")?;
+    let modules_side = code.width();
+    let colors = code.to_colors();
+
+    let side = (modules_side + QUIET_ZONE * 2) * MODULE_SCALE;
+    let mut image = ColorImage::new([side, side], Color32::WHITE);
+
+    for y in 0..modules_side {
+        for x in 0..modules_side {
+            let color = colors[y * modules_side + x].select(Color32::BLACK, Color32::WHITE);
+            let base_x = (x + QUIET_ZONE) * MODULE_SCALE;
+            let base_y = (y + QUIET_ZONE) * MODULE_SCALE;
+
+            for dy in 0..MODULE_SCALE {
+                for dx in 0..MODULE_SCALE {
+                    image[(base_x + dx, base_y + dy)] = color;
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
@@ -13,4 +13,10 @@ pub enum AppState {
 
     PublishUser,
     WaitForRegisterConfirmation,
+
+    Reconnecting { attempt: u32 },
+    Disconnected,
+
+    IncomingFileRequest { from: String, ticket: String },
+    Downloading { ticket: String },
 }
@@ -5,9 +5,13 @@ use crate::MyApp;
 
 impl MyApp {
     pub fn show_error_toast(&mut self, text: impl Into<WidgetText>) {
+        self.show_toast(text, ToastKind::Error);
+    }
+
+    pub fn show_toast(&mut self, text: impl Into<WidgetText>, kind: ToastKind) {
         self.toasts.add(Toast {
             text: text.into(),
-            kind: ToastKind::Error,
+            kind,
             options: ToastOptions::default()
                 .duration_in_seconds(3.)
                 .show_progress(true),